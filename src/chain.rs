@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) 2024  Hewlett Packard Enterprise Development LP
+//
+// Author: Geoffrey Ndu (gtn@hpe.com)
+
+//! SEV-SNP certificate chain and report signature verification
+//!
+//! The attestation report's `report_data` only proves that the caller's
+//! nonce and manifest were hashed into a report; it says nothing about
+//! whether the report itself was produced by genuine AMD silicon. This
+//! module closes that gap by fetching the VCEK certificate for the
+//! reporting chip from AMD's Key Distribution Service (KDS), or reading
+//! it from the configfs-tsm auxblob when one is given, chaining it
+//! up through the ASK to the ARK, verifying that chain against AMD's
+//! well-known root, and finally verifying the report's own ECDSA P-384
+//! signature with the VCEK public key.
+
+use sev::certs::snp::{ca, Certificate, Chain, Verifiable};
+use sev::firmware::guest::{AttestationReport, TcbVersion};
+
+const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
+
+/// Builds the KDS VCEK URL for a chip/TCB pair.
+///
+/// See the "SEV-SNP: KDS Interface Specification" for the URL layout:
+/// `/vcek/v1/<product>/<hwid>?blSPL=..&teeSPL=..&snpSPL=..&ucodeSPL=..`
+fn vcek_url(product: &str, chip_id: &[u8], tcb: &TcbVersion) -> String {
+    format!(
+        "{KDS_CERT_SITE}/vcek/v1/{product}/{}?blSPL={}&teeSPL={}&snpSPL={}&ucodeSPL={}",
+        hex::encode(chip_id),
+        tcb.bootloader,
+        tcb.tee,
+        tcb.snp,
+        tcb.microcode
+    )
+}
+
+fn fetch_der(url: &str) -> Vec<u8> {
+    reqwest::blocking::get(url)
+        .unwrap_or_else(|e| panic!("Failed to fetch {url}: {e}"))
+        .error_for_status()
+        .unwrap_or_else(|e| panic!("KDS returned an error for {url}: {e}"))
+        .bytes()
+        .unwrap_or_else(|e| panic!("Failed to read response body from {url}: {e}"))
+        .to_vec()
+}
+
+/// Fetches the VCEK for the given chip/TCB and the ARK/ASK chain for the
+/// product line, and assembles them into a `sev::certs::snp::Chain`.
+fn fetch_chain(product: &str, chip_id: &[u8], tcb: &TcbVersion) -> Chain {
+    println!(
+        "Fetching VCEK for chip_id {} from AMD KDS",
+        hex::encode(chip_id)
+    );
+    let vcek = Certificate::from_der(&fetch_der(&vcek_url(product, chip_id, tcb)))
+        .expect("Failed to parse VCEK certificate from KDS response");
+
+    println!("Fetching ARK/ASK certificate chain for {product} from AMD KDS");
+    let cert_chain_url = format!("{KDS_CERT_SITE}/vcek/v1/{product}/cert_chain");
+    let pem = fetch_der(&cert_chain_url);
+    let (ark, ask) = ca::Chain::from_pem_bytes(&pem)
+        .expect("Failed to parse ARK/ASK certificate chain from KDS response")
+        .into();
+
+    Chain {
+        ca: ca::Chain { ark, ask },
+        vek: vcek,
+    }
+}
+
+/// GUIDs identifying each certificate's entry in the PSP extended-report
+/// `cert_table`, as defined by the Linux `sev-guest` driver and the
+/// SEV-SNP GHCB spec. Each `snp_cert_table_entry` is `{guid: [u8; 16],
+/// offset: u32, length: u32}` (offset/length little-endian, relative to
+/// the start of the blob), and the table is terminated by an all-zero
+/// GUID entry.
+const VCEK_GUID: [u8; 16] = [
+    0x8d, 0x75, 0xda, 0x63, 0x64, 0xe6, 0x64, 0x45, 0xad, 0xc5, 0xf4, 0xb9, 0x3b, 0xe8, 0xac, 0xcd,
+];
+const ASK_GUID: [u8; 16] = [
+    0x79, 0xb3, 0xb7, 0x4a, 0xac, 0xbb, 0xe4, 0x4f, 0xa0, 0x2f, 0x05, 0xae, 0xf3, 0x27, 0xc7, 0x82,
+];
+const ARK_GUID: [u8; 16] = [
+    0xa4, 0x06, 0xb4, 0xc0, 0x03, 0xa8, 0x52, 0x49, 0x97, 0x43, 0x3f, 0xb6, 0x01, 0x4c, 0xd0, 0xae,
+];
+
+const CERT_TABLE_ENTRY_LEN: usize = 16 + 4 + 4;
+
+/// One parsed `snp_cert_table_entry`.
+struct CertTableEntry {
+    guid: [u8; 16],
+    offset: usize,
+    length: usize,
+}
+
+/// Parses the GUID-keyed cert_table at the start of `auxblob` into its
+/// entries, stopping at the first all-zero GUID (or the end of the
+/// blob).
+fn parse_cert_table(auxblob: &[u8]) -> Vec<CertTableEntry> {
+    let mut entries = Vec::new();
+    for chunk in auxblob.chunks_exact(CERT_TABLE_ENTRY_LEN) {
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&chunk[..16]);
+        if guid == [0u8; 16] {
+            break;
+        }
+        let offset = u32::from_le_bytes(chunk[16..20].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(chunk[20..24].try_into().unwrap()) as usize;
+        entries.push(CertTableEntry {
+            guid,
+            offset,
+            length,
+        });
+    }
+    entries
+}
+
+/// Looks up `guid` in `entries` and returns the corresponding DER bytes
+/// from `auxblob`.
+fn cert_bytes<'a>(
+    auxblob: &'a [u8],
+    entries: &[CertTableEntry],
+    guid: [u8; 16],
+    name: &str,
+) -> &'a [u8] {
+    let entry = entries
+        .iter()
+        .find(|e| e.guid == guid)
+        .unwrap_or_else(|| panic!("auxblob cert_table has no entry for {name}"));
+    auxblob
+        .get(entry.offset..entry.offset + entry.length)
+        .unwrap_or_else(|| panic!("auxblob cert_table entry for {name} is out of bounds"))
+}
+
+/// Builds a `Chain` from the configfs-tsm `auxblob` attribute instead of
+/// the network, by looking the VCEK/ASK/ARK DER certificates up in its
+/// GUID-keyed cert_table.
+fn chain_from_auxblob(auxblob: &[u8]) -> Chain {
+    println!("Using certificate chain from configfs-tsm auxblob instead of AMD KDS");
+    let entries = parse_cert_table(auxblob);
+
+    let vcek = Certificate::from_der(cert_bytes(auxblob, &entries, VCEK_GUID, "VCEK"))
+        .expect("Failed to parse VCEK from auxblob cert_table");
+    let ask = Certificate::from_der(cert_bytes(auxblob, &entries, ASK_GUID, "ASK"))
+        .expect("Failed to parse ASK from auxblob cert_table");
+    let ark = Certificate::from_der(cert_bytes(auxblob, &entries, ARK_GUID, "ARK"))
+        .expect("Failed to parse ARK from auxblob cert_table");
+
+    Chain {
+        ca: ca::Chain { ark, ask },
+        vek: vcek,
+    }
+}
+
+/// Report structure versions this program knows the `sev` crate's
+/// `(&Chain, &AttestationReport)` verifier supports. The signed-range
+/// and signature layout are version-dependent in the SEV-SNP ABI, so
+/// refuse to guess on a version we haven't checked against the crate.
+const SUPPORTED_REPORT_VERSIONS: &[u32] = &[2, 3];
+
+/// Verifies the SEV-SNP certificate chain (VCEK <- ASK <- ARK) and the
+/// attestation report's signature against the resulting VCEK, using the
+/// `sev` crate's own `Verifiable` implementations rather than hand-
+/// slicing the report. Returns `Some(true)`/`Some(false)` if verification
+/// ran and passed/failed, or `None` if it was skipped outright (an
+/// unsupported report version) - distinct from a genuine failure, since a
+/// relying party parsing `CheckResults.signature_chain` needs to tell
+/// "never attempted" apart from "cryptographically failed". When
+/// `auxblob` is `Some`, the chain is built from it instead of fetched
+/// from AMD's KDS over the network.
+pub fn verify(report: &AttestationReport, product: &str, auxblob: Option<&[u8]>) -> Option<bool> {
+    println!("\nVerifying SEV-SNP certificate chain and report signature (--verify-chain)");
+
+    if !SUPPORTED_REPORT_VERSIONS.contains(&report.version) {
+        println!(
+            "Report version {} is not one this program's chain verification has been checked against ({:?}); skipping --verify-chain",
+            report.version, SUPPORTED_REPORT_VERSIONS
+        );
+        return None;
+    }
+
+    let chain = match auxblob {
+        Some(auxblob) => chain_from_auxblob(auxblob),
+        None => fetch_chain(product, &report.chip_id, &report.reported_tcb),
+    };
+
+    let chain_ok = match chain.verify() {
+        Ok(()) => {
+            println!("VCEK <- ASK <- ARK chain verified against AMD root of trust!");
+            true
+        }
+        Err(e) => {
+            println!("SEV-SNP certificate chain (VCEK <- ASK <- ARK) failed to verify against AMD root: {e}");
+            false
+        }
+    };
+
+    let report_ok = match (&chain, report).verify() {
+        Ok(()) => {
+            println!("AttestationReport signature verified against VCEK!\n");
+            true
+        }
+        Err(e) => {
+            println!("AttestationReport signature did not verify against the VCEK: {e}");
+            false
+        }
+    };
+
+    Some(chain_ok && report_ok)
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) 2024  Hewlett Packard Enterprise Development LP
+//
+// Author: Geoffrey Ndu (gtn@hpe.com)
+
+//! EK private-key residence challenge via TPM2_MakeCredential /
+//! TPM2_ActivateCredential
+//!
+//! Matching the EK public against the manifest only proves the *public*
+//! half lines up; a replayed manifest would pass the same check. To
+//! prove that this specific, live vTPM holds the matching private key we
+//! wrap a random secret under the EK's name with TPM2_MakeCredential and
+//! ask the vTPM to recover it with TPM2_ActivateCredential, run under a
+//! policy session satisfying the EK's default endorsement-auth policy.
+//! Only a TPM holding the EK private key can recover the original
+//! secret.
+
+use rand::RngCore;
+use tss_esapi::{
+    handles::KeyHandle,
+    structures::{Digest, Name},
+    Context,
+};
+
+use crate::ek_policy;
+
+const CHALLENGE_SECRET_LEN: usize = 32;
+
+/// Generates a random secret, wraps it for `ek_name` with
+/// TPM2_MakeCredential, and has the vTPM at `ek_handle` recover it with
+/// TPM2_ActivateCredential. Returns `true` only if the recovered secret
+/// matches the one generated; TPM communication failures still panic,
+/// since those mean the test itself couldn't run rather than that it
+/// failed.
+pub fn prove_ek_residence(context: &mut Context, ek_handle: KeyHandle, ek_name: Name) -> bool {
+    println!("\nProving EK private-key residence via TPM2_MakeCredential/TPM2_ActivateCredential");
+
+    let mut secret = vec![0u8; CHALLENGE_SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let credential = Digest::try_from(secret.clone()).expect("Failed to build credential digest");
+
+    let (credential_blob, encrypted_secret) = context
+        .execute_without_session(|ctx| ctx.make_credential(ek_handle, credential, ek_name))
+        .expect("TPM2_MakeCredential failed");
+
+    // The EK's default TCG template requires a policy session satisfying
+    // PolicySecret against the endorsement hierarchy, so ActivateCredential
+    // can't run under a plain password/null-auth session.
+    let policy_session = ek_policy::start(context);
+
+    let recovered = context
+        .execute_with_session(Some(policy_session), |ctx| {
+            ctx.activate_credential(ek_handle, ek_handle, credential_blob, encrypted_secret)
+        })
+        .expect("TPM2_ActivateCredential failed");
+    context
+        .flush_context(policy_session.handle().into())
+        .expect("Failed to flush activate-credential policy session");
+
+    let recovered_ok = recovered.as_bytes() == secret.as_slice();
+    if recovered_ok {
+        println!(
+            "vTPM recovered the original secret - it demonstrably holds the EK private key!\n"
+        );
+    } else {
+        println!("Recovered credential secret did NOT match the one generated - vTPM does not hold the EK private key!\n");
+    }
+    recovered_ok
+}
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) 2024  Hewlett Packard Enterprise Development LP
+//
+// Author: Geoffrey Ndu (gtn@hpe.com)
+
+//! Shared helper for operations that need an endorsement-hierarchy
+//! policy session.
+//!
+//! The EK's default TCG template requires a policy session satisfying
+//! `TPM2_PolicySecret` against the endorsement hierarchy before the key
+//! can be used (to create/load children of it, or to activate a
+//! credential against it). Both the residence-proof and quote flows need
+//! this, so it lives here instead of being duplicated.
+
+use tss_esapi::{
+    constants::SessionType,
+    interface_types::{
+        algorithm::HashingAlgorithm, resource_handles::Hierarchy, session_handles::PolicySession,
+    },
+    sessions::AuthSession,
+    structures::SymmetricDefinition,
+    Context,
+};
+
+/// Starts a policy session and satisfies it with `TPM2_PolicySecret`
+/// against the endorsement hierarchy, returning the session handle ready
+/// to be used as the auth session for an EK operation.
+pub fn start(context: &mut Context) -> AuthSession {
+    let session_handle = context
+        .start_auth_session(
+            None,
+            None,
+            None,
+            SessionType::Policy,
+            SymmetricDefinition::AES_128_CFB,
+            HashingAlgorithm::Sha256,
+        )
+        .expect("Failed to start policy session")
+        .expect("TPM did not return a policy session handle");
+    let policy_session =
+        PolicySession::try_from(session_handle).expect("Session handle was not a policy session");
+
+    context
+        .execute_with_nullauth_session(|ctx| {
+            ctx.policy_secret(
+                policy_session,
+                Hierarchy::Endorsement.into(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                None,
+            )
+        })
+        .expect("TPM2_PolicySecret against the endorsement hierarchy failed");
+
+    session_handle
+}
@@ -13,12 +13,12 @@
 //! export TCTI="device:/dev/tpmrm0" and if using sudo use sudo -E
 //! The program will try adding /dev/tpmrm0 to the TCTI device path if it does not exist
 //!
+use clap::Parser;
 use pretty_hex::*;
 use sev::firmware::guest::AttestationReport;
 use sha2::{Digest, Sha512};
 use std::{fs, str::FromStr};
 use tempfile::tempdir_in;
-use clap::Parser;
 
 use tss_esapi::{
     abstraction::ek,
@@ -27,12 +27,149 @@ use tss_esapi::{
     Context, TctiNameConf,
 };
 
+mod chain;
+mod credential;
+mod ek_policy;
+mod output;
+mod quote;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    #[arg(short, long, action = clap::ArgAction::SetTrue, 
+    #[arg(short, long, action = clap::ArgAction::SetTrue,
         help = "Use configfs-tsm svsm attribute, required by pre v6.10 kernels")]
     svsm_attribute: bool,
+
+    #[arg(long, action = clap::ArgAction::SetTrue,
+        help = "Verify the SEV-SNP VCEK<-ASK<-ARK certificate chain against AMD's root and the report signature against the VCEK, using certificates from the configfs-tsm auxblob if present, otherwise fetched from AMD's KDS")]
+    verify_chain: bool,
+
+    #[arg(
+        long,
+        default_value = "Milan",
+        help = "AMD product line to use for the KDS VCEK/cert-chain URLs with --verify-chain (e.g. Milan, Genoa, Bergamo, Siena); the report itself does not carry a usable product-line field"
+    )]
+    product: String,
+
+    #[arg(
+        long,
+        default_value = "sha256",
+        help = "PCR bank to quote: sha256, sha384, or sha1"
+    )]
+    pcr_bank: String,
+
+    #[arg(
+        long,
+        default_value = "0,1,7",
+        help = "PCR indices to quote, comma-separated (0,1,7) or a JSON array ([0,1,7])"
+    )]
+    pcr_ids: String,
+
+    #[arg(
+        long,
+        value_name = "HEX",
+        help = "Hex-encoded 64-byte userdata/nonce to bind into report_data, TSM convention (conflicts with --nonce-file)"
+    )]
+    nonce: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "nonce",
+        help = "Path to a file containing a hex-encoded 64-byte userdata/nonce to bind into report_data"
+    )]
+    nonce_file: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "rsa",
+        help = "Endorsement key algorithm and TCG default template to use: rsa (RSA-2048) or ecc (ECC NIST P-256)"
+    )]
+    ek_alg: String,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "VMPL to generate the report at, written to the configfs-tsm privlevel attribute if present on this kernel"
+    )]
+    privlevel: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write a structured attestation bundle (raw blobs, decoded report, EK public, and check results) to PATH"
+    )]
+    output: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "json",
+        help = "Format for --output; only json is currently supported"
+    )]
+    format: String,
+}
+
+/// Parses `--ek-alg` into the tss-esapi algorithm selector for the TCG
+/// default EK template.
+fn parse_ek_alg(alg: &str) -> AsymmetricAlgorithm {
+    match alg.to_ascii_lowercase().as_str() {
+        "rsa" => AsymmetricAlgorithm::Rsa,
+        "ecc" => AsymmetricAlgorithm::Ecc,
+        other => panic!("Unsupported --ek-alg {other:?}: expected rsa or ecc"),
+    }
+}
+
+/// Length in bytes of the `inblob`/`report_data` nonce the TSM ABI expects.
+const NONCE_LEN: usize = 64;
+
+/// Resolves the nonce to use for this run: a caller-supplied `--nonce`
+/// or `--nonce-file` (both hex-encoded, TSM userdata-plus-nonce
+/// convention), falling back to the legacy fixed `0xff` block when
+/// neither is given. Panics if the decoded value isn't exactly
+/// `NONCE_LEN` bytes.
+fn resolve_nonce(cli: &Cli) -> [u8; NONCE_LEN] {
+    let hex_str = if let Some(nonce) = &cli.nonce {
+        Some(nonce.clone())
+    } else if let Some(path) = &cli.nonce_file {
+        Some(fs::read_to_string(path).expect("Failed to read --nonce-file"))
+    } else {
+        None
+    };
+
+    let bytes = match hex_str {
+        Some(s) => hex::decode(s.trim()).expect("--nonce/--nonce-file must be valid hex"),
+        None => vec![0xff; NONCE_LEN],
+    };
+
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .unwrap_or_else(|_| panic!("Nonce must decode to exactly {NONCE_LEN} bytes, got {len}"))
+}
+
+/// Parses `--pcr-bank` into the tss-esapi hashing algorithm it selects.
+fn parse_pcr_bank(bank: &str) -> tss_esapi::interface_types::algorithm::HashingAlgorithm {
+    use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+    match bank.to_ascii_lowercase().as_str() {
+        "sha256" => HashingAlgorithm::Sha256,
+        "sha384" => HashingAlgorithm::Sha384,
+        "sha1" => HashingAlgorithm::Sha1,
+        other => panic!("Unsupported --pcr-bank {other:?}: expected sha256, sha384, or sha1"),
+    }
+}
+
+/// Parses `--pcr-ids`, accepting either a comma-separated list (`0,1,7`)
+/// or a JSON array (`[0,1,7]`).
+fn parse_pcr_ids(ids: &str) -> Vec<u8> {
+    let trimmed = ids.trim();
+    if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed).expect("Failed to parse --pcr-ids as a JSON array")
+    } else {
+        trimmed
+            .split(',')
+            .map(|s| s.trim().parse().expect("--pcr-ids must be integers 0-23"))
+            .collect()
+    }
 }
 
 fn main() {
@@ -49,7 +186,7 @@ fn main() {
         println!("Using configfs-tsm svsm attribute found in pre Linux v6.10");
         // write 1 to svsm file
         fs::write(tmp_dir.path().join("svsm"), "1")
-        .expect("Failed to write to svsm attribute file");
+            .expect("Failed to write to svsm attribute file");
     } else {
         // write "svsm" to service_provider file
         println!("Using configfs-tsm service_provider attribute added in Linux v6.10");
@@ -57,15 +194,24 @@ fn main() {
             .expect("Failed to write to service_provider attribute file");
     }
 
-    let nonce: [u8; 64] = [0xff; 64];
+    if let Some(privlevel) = cli.privlevel {
+        // privlevel is not exposed by older kernels; degrade gracefully
+        // like the svsm/service_provider attribute split above
+        match fs::write(tmp_dir.path().join("privlevel"), privlevel.to_string()) {
+            Ok(()) => println!("Requested report at VMPL {privlevel} via configfs-tsm privlevel attribute"),
+            Err(e) => println!("Kernel does not expose the configfs-tsm privlevel attribute, ignoring --privlevel: {e}"),
+        }
+    }
+
+    let nonce: [u8; NONCE_LEN] = resolve_nonce(&cli);
     // write nonce to inblob file
     fs::write(tmp_dir.path().join("inblob"), nonce).expect("Failed to write to inblob file");
-    
+
     let attest_vtpm_guid = "c476f1eb-0123-45a5-9641-b4e7dde5bfe3";
     // write attest_vtpm_guid to service_guid file
     fs::write(tmp_dir.path().join("service_guid"), attest_vtpm_guid)
         .expect("Failed to write to service_guid file");
-    
+
     // read outblob file
     let outblob = fs::read(tmp_dir.path().join("outblob")).expect("Failed to read outblob file");
     println!("outblob: {:?}", outblob.hex_dump());
@@ -78,16 +224,35 @@ fn main() {
         fs::read(tmp_dir.path().join("manifestblob")).expect("Failed to read manifest file");
     println!("manifest: {:?}", manifest.hex_dump());
 
+    // auxblob is not exposed by older kernels; degrade gracefully like
+    // the svsm/service_provider attribute split above
+    let auxblob = match fs::read(tmp_dir.path().join("auxblob")) {
+        Ok(bytes) => {
+            println!("auxblob: {:?}", bytes.hex_dump());
+            Some(bytes)
+        }
+        Err(e) => {
+            println!("Kernel does not expose the configfs-tsm auxblob attribute: {e}");
+            None
+        }
+    };
+
     // parse attestation report in outblob
     let report: AttestationReport = bincode::deserialize(&outblob).unwrap();
     println!("report: {}", report);
 
-    println!("Verifying that the RSA 2048 EK public in the report matches one created in vTPM using TCG Profile");
+    let signature_chain_checked = if cli.verify_chain {
+        chain::verify(&report, &cli.product, auxblob.as_deref())
+    } else {
+        None
+    };
+
+    let algorithm = parse_ek_alg(&cli.ek_alg);
+    println!("Verifying that the {} EK public in the report matches one created in vTPM using TCG Profile", cli.ek_alg);
     println!("Creating EK public key using TCG Profile and comparing it to the one in the report");
     println!("\nUsing TSS 2.0 Enhanced System API Rust Wrapper, tss-esapi");
 
     //create ek using TCG Profile
-    let algorithm = AsymmetricAlgorithm::Rsa;
     let ek_public = ek::create_ek_public_from_default_template(algorithm, None)
         .expect("Failed to create ek public key");
     println!(
@@ -127,8 +292,20 @@ fn main() {
     println!("tmpt_public: {:?}", ekpub_tmpt_pub.hex_dump());
 
     // check that ek pub from tpm matches ek pub from manifest
-    assert_eq!(ekpub_tmpt_pub, manifest);
-    println!("\n\nEK public key in the report matches the one created in vTPM!\n");
+    let ek_public_match = ekpub_tmpt_pub == manifest;
+    if ek_public_match {
+        println!("\n\nEK public key in the report matches the one created in vTPM!\n");
+    } else {
+        println!("\n\nEK public key in the report does NOT match the one created in vTPM!\n");
+    }
+
+    // prove that the vTPM actually holds the EK private key, not just
+    // that its public half matches the manifest
+    let ek_name = context
+        .tr_get_name(ek.key_handle.into())
+        .expect("Failed to get EK object name");
+    let make_activate_credential =
+        credential::prove_ek_residence(&mut context, ek.key_handle, ek_name);
 
     // recalculate Sha512(nonce||manifest)
     println!(
@@ -137,7 +314,10 @@ fn main() {
 
     //concatenate nonce and manifest
     let hash_in = nonce.to_vec();
-    let hash_in = hash_in.into_iter().chain(manifest).collect::<Vec<u8>>();
+    let hash_in = hash_in
+        .into_iter()
+        .chain(manifest.clone())
+        .collect::<Vec<u8>>();
     println!("nonce||manifest: {:?}", hash_in.hex_dump());
     let sha512 = Sha512::digest(&hash_in);
     println!(
@@ -147,11 +327,50 @@ fn main() {
 
     println!("report.report_data: {:?}", report.report_data.hex_dump());
 
-    println!("Sha512(nonce||manifest) matches one in the report.report_data");
-
     //verify that the hash matches the digest in the report
-    assert_eq!(sha512.as_slice(), report.report_data);
+    let report_data_match = sha512.as_slice() == report.report_data;
+    if report_data_match {
+        println!("Sha512(nonce||manifest) matches one in the report.report_data");
+    } else {
+        println!("Sha512(nonce||manifest) does NOT match one in the report.report_data");
+    }
+
+    // bind selected PCRs into the attestation with TPM2_Quote
+    let pcr_bank = parse_pcr_bank(&cli.pcr_bank);
+    let pcr_ids = parse_pcr_ids(&cli.pcr_ids);
+    let quote_ok = quote::quote_and_verify(&mut context, ek.key_handle, &nonce, pcr_bank, &pcr_ids);
+
+    let checks = output::CheckResults {
+        report_data_match,
+        ek_public_match,
+        make_activate_credential,
+        quote: quote_ok,
+        signature_chain: signature_chain_checked,
+    };
+
+    if let Some(path) = &cli.output {
+        assert_eq!(
+            cli.format, "json",
+            "--format {:?} is not supported, only json is",
+            cli.format
+        );
+        let bundle = output::Bundle::new(
+            &outblob,
+            &manifest,
+            auxblob.as_deref(),
+            &nonce,
+            &report,
+            &ekpub_tmpt_pub,
+            checks.clone(),
+        );
+        output::write(&bundle, path);
+    }
 
     //delete temp directory
     drop(tmp_dir);
+
+    if !checks.all_passed() {
+        eprintln!("\nOne or more attestation checks failed: {checks:?}");
+        std::process::exit(1);
+    }
 }
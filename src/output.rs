@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) 2024  Hewlett Packard Enterprise Development LP
+//
+// Author: Geoffrey Ndu (gtn@hpe.com)
+
+//! Structured, machine-readable attestation bundle
+//!
+//! Everything this program checks is otherwise only visible as
+//! `println!`/`hex_dump()` output on stdout, which isn't something an
+//! automated verifier or CI pipeline can consume. This module collects
+//! the raw blobs, the decoded report fields, and the pass/fail status of
+//! each check into one JSON document that can be archived alongside
+//! `report.bin`.
+
+use serde::Serialize;
+use sev::firmware::guest::AttestationReport;
+use std::path::Path;
+
+/// Pass/fail status of each verification step this program performs.
+/// `signature_chain` is `None` when the step never ran - either
+/// `--verify-chain` wasn't passed, or `chain::verify` skipped it outright
+/// (e.g. an unsupported report version) - which must stay distinct from
+/// `Some(false)`, a genuine chain/signature failure.
+#[derive(Serialize, Clone, Debug)]
+pub struct CheckResults {
+    pub report_data_match: bool,
+    pub ek_public_match: bool,
+    pub make_activate_credential: bool,
+    pub quote: bool,
+    pub signature_chain: Option<bool>,
+}
+
+impl CheckResults {
+    /// `true` only if every check that actually ran passed.
+    /// `signature_chain` is excluded when it's `None` (the step never
+    /// ran), same as the other fields would be if this program tracked
+    /// "didn't run" for them.
+    pub fn all_passed(&self) -> bool {
+        self.report_data_match
+            && self.ek_public_match
+            && self.make_activate_credential
+            && self.quote
+            && self.signature_chain.unwrap_or(true)
+    }
+}
+
+/// The subset of `AttestationReport` fields relevant to a relying party,
+/// decoded to plain/hex values so they serialize directly to JSON.
+#[derive(Serialize)]
+pub struct ReportSummary {
+    pub version: u32,
+    pub guest_svn: u32,
+    pub policy: String,
+    pub vmpl: u32,
+    pub signature_algo: String,
+    pub report_data: String,
+    pub measurement: String,
+    pub chip_id: String,
+    pub reported_tcb_bootloader: u8,
+    pub reported_tcb_tee: u8,
+    pub reported_tcb_snp: u8,
+    pub reported_tcb_microcode: u8,
+}
+
+impl From<&AttestationReport> for ReportSummary {
+    fn from(report: &AttestationReport) -> Self {
+        ReportSummary {
+            version: report.version,
+            guest_svn: report.guest_svn,
+            policy: format!("{:?}", report.policy),
+            vmpl: report.vmpl,
+            signature_algo: format!("{:?}", report.sig_algo),
+            report_data: hex::encode(report.report_data),
+            measurement: hex::encode(report.measurement),
+            chip_id: hex::encode(report.chip_id),
+            reported_tcb_bootloader: report.reported_tcb.bootloader,
+            reported_tcb_tee: report.reported_tcb.tee,
+            reported_tcb_snp: report.reported_tcb.snp,
+            reported_tcb_microcode: report.reported_tcb.microcode,
+        }
+    }
+}
+
+/// The full attestation bundle written by `--output`.
+#[derive(Serialize)]
+pub struct Bundle {
+    pub outblob: String,
+    pub manifestblob: String,
+    pub auxblob: Option<String>,
+    pub nonce: String,
+    pub report: ReportSummary,
+    pub ek_public: String,
+    pub checks: CheckResults,
+}
+
+impl Bundle {
+    pub fn new(
+        outblob: &[u8],
+        manifest: &[u8],
+        auxblob: Option<&[u8]>,
+        nonce: &[u8],
+        report: &AttestationReport,
+        ek_public: &[u8],
+        checks: CheckResults,
+    ) -> Self {
+        Bundle {
+            outblob: hex::encode(outblob),
+            manifestblob: hex::encode(manifest),
+            auxblob: auxblob.map(hex::encode),
+            nonce: hex::encode(nonce),
+            report: ReportSummary::from(report),
+            ek_public: hex::encode(ek_public),
+            checks,
+        }
+    }
+}
+
+/// Serializes `bundle` as pretty-printed JSON to `path`.
+pub fn write(bundle: &Bundle, path: &Path) {
+    let json =
+        serde_json::to_string_pretty(bundle).expect("Failed to serialize attestation bundle");
+    std::fs::write(path, json).expect("Failed to write attestation bundle");
+    println!(
+        "\nWrote structured attestation bundle to {}",
+        path.display()
+    );
+}
@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) 2024  Hewlett Packard Enterprise Development LP
+//
+// Author: Geoffrey Ndu (gtn@hpe.com)
+
+//! TPM2_Quote over caller-selected PCRs
+//!
+//! EK identity alone says nothing about the vTPM's current measured-boot
+//! state. This module creates a restricted attestation key (AK) as a
+//! child of the EK, quotes a caller-selected PCR bank/index list using
+//! the configfs nonce as qualifying data, and checks both the returned
+//! `TPMS_ATTEST` signature and the PCR digest it embeds against a fresh
+//! `pcr_read` of the same selection.
+
+use pretty_hex::*;
+use sha1::Sha1;
+use sha2::{Digest as ShaDigestTrait, Sha256, Sha384};
+use tss_esapi::{
+    attributes::ObjectAttributesBuilder,
+    handles::KeyHandle,
+    interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm},
+    structures::{
+        AttestInfo, Data, PcrSelectionListBuilder, PcrSlot, Public, PublicBuilder, PublicKeyRsa,
+        PublicRsaParametersBuilder, RsaExponent, RsaScheme, SignatureScheme,
+    },
+    traits::Marshall,
+    Context,
+};
+
+use crate::ek_policy;
+
+/// Maps a 0-based PCR index (as accepted on the `--pcr-ids` flag) to the
+/// `PcrSlot` the tss-esapi selection builder expects.
+fn pcr_slot(index: u8) -> PcrSlot {
+    match index {
+        0 => PcrSlot::Slot0,
+        1 => PcrSlot::Slot1,
+        2 => PcrSlot::Slot2,
+        3 => PcrSlot::Slot3,
+        4 => PcrSlot::Slot4,
+        5 => PcrSlot::Slot5,
+        6 => PcrSlot::Slot6,
+        7 => PcrSlot::Slot7,
+        8 => PcrSlot::Slot8,
+        9 => PcrSlot::Slot9,
+        10 => PcrSlot::Slot10,
+        11 => PcrSlot::Slot11,
+        12 => PcrSlot::Slot12,
+        13 => PcrSlot::Slot13,
+        14 => PcrSlot::Slot14,
+        15 => PcrSlot::Slot15,
+        16 => PcrSlot::Slot16,
+        17 => PcrSlot::Slot17,
+        18 => PcrSlot::Slot18,
+        19 => PcrSlot::Slot19,
+        20 => PcrSlot::Slot20,
+        21 => PcrSlot::Slot21,
+        22 => PcrSlot::Slot22,
+        23 => PcrSlot::Slot23,
+        other => panic!("PCR index {other} is out of range (0-23)"),
+    }
+}
+
+/// Hash algorithm of the AK's signing scheme. Per the TPM2 spec,
+/// TPM2_Quote's `pcrDigest` is always hashed with the *signing scheme's*
+/// hash algorithm, not the hash algorithm of the PCR bank being quoted -
+/// so this must stay in lockstep between `ak_public()` and the
+/// verification recompute below, independent of `--pcr-bank`.
+const AK_SCHEME_HASH: HashingAlgorithm = HashingAlgorithm::Sha256;
+
+/// Builds the default TCG "restricted RSA signing key" template used for
+/// an attestation key (AK), analogous to the EK template but for signing
+/// rather than decryption.
+fn ak_public() -> Public {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .with_sign_encrypt(true)
+        .with_restricted(true)
+        .build()
+        .expect("Failed to build AK object attributes");
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Rsa)
+        .with_name_hashing_algorithm(AK_SCHEME_HASH)
+        .with_object_attributes(object_attributes)
+        .with_rsa_parameters(
+            PublicRsaParametersBuilder::new_restricted_signing_key(
+                tss_esapi::structures::SymmetricDefinitionObject::Null,
+                RsaScheme::create(
+                    tss_esapi::interface_types::algorithm::RsaSchemeAlgorithm::RsaSsa,
+                    Some(AK_SCHEME_HASH),
+                )
+                .expect("Failed to build RSA signing scheme"),
+                2048,
+                RsaExponent::default(),
+            )
+            .expect("Failed to build AK RSA parameters"),
+        )
+        .with_rsa_unique_identifier(PublicKeyRsa::default())
+        .build()
+        .expect("Failed to build AK public template")
+}
+
+/// Hashes `data` with the algorithm `bank`. The quote check calls it
+/// with `AK_SCHEME_HASH`, the algorithm of the PCR digest the TPM embeds
+/// in a quote, not with the `--pcr-bank` algorithm.
+fn hash_with_bank(bank: HashingAlgorithm, data: &[u8]) -> Vec<u8> {
+    match bank {
+        HashingAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        HashingAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+        HashingAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+        other => panic!("Unsupported PCR bank {other:?}"),
+    }
+}
+
+/// Creates an AK under the EK, quotes `pcr_ids` in `bank` using
+/// `qualifying_data`, and checks the quote's signature and embedded PCR
+/// digest against a fresh `pcr_read`. Returns `true` only if both the
+/// signature and the PCR digest check out; TPM communication/setup
+/// failures (session, create, load, quote, pcr_read) still panic, since
+/// those mean the test itself couldn't run rather than that it failed.
+pub fn quote_and_verify(
+    context: &mut Context,
+    ek_handle: KeyHandle,
+    qualifying_data: &[u8],
+    bank: HashingAlgorithm,
+    pcr_ids: &[u8],
+) -> bool {
+    println!("\nCreating attestation key (AK) under the EK and running TPM2_Quote");
+
+    let slots: Vec<PcrSlot> = pcr_ids.iter().copied().map(pcr_slot).collect();
+    let pcr_selection_list = PcrSelectionListBuilder::new()
+        .with_selection(bank, &slots)
+        .build()
+        .expect("Failed to build PCR selection list");
+
+    let create_session = ek_policy::start(context);
+    let ak_create = context
+        .execute_with_session(Some(create_session), |ctx| {
+            ctx.create(ek_handle, ak_public(), None, None, None, None)
+        })
+        .expect("Failed to create AK under EK");
+    context
+        .flush_context(create_session.handle().into())
+        .expect("Failed to flush AK create policy session");
+
+    let load_session = ek_policy::start(context);
+    let ak_handle = context
+        .execute_with_session(Some(load_session), |ctx| {
+            ctx.load(ek_handle, ak_create.out_private, ak_create.out_public)
+        })
+        .expect("Failed to load AK");
+    context
+        .flush_context(load_session.handle().into())
+        .expect("Failed to flush AK load policy session");
+
+    let qualifying_data =
+        Data::try_from(qualifying_data.to_vec()).expect("Qualifying data too large for TPM2B_DATA");
+
+    let (attest, signature) = context
+        .execute_with_nullauth_session(|ctx| {
+            ctx.quote(
+                ak_handle,
+                qualifying_data,
+                SignatureScheme::Null,
+                pcr_selection_list.clone(),
+            )
+        })
+        .expect("TPM2_Quote failed");
+
+    println!(
+        "attest: {:?}",
+        attest.marshall().unwrap_or_default().hex_dump()
+    );
+
+    let attest_digest = tss_esapi::structures::Digest::try_from(
+        Sha256::digest(attest.marshall().expect("Failed to marshall TPMS_ATTEST")).to_vec(),
+    )
+    .expect("Failed to build attest digest");
+    let signature_ok = context
+        .execute_with_nullauth_session(|ctx| {
+            ctx.verify_signature(ak_handle, attest_digest, signature)
+        })
+        .is_ok();
+    if signature_ok {
+        println!("Quote signature verified against AK public key!");
+    } else {
+        println!("Quote signature did NOT verify against the AK public key!");
+    }
+
+    let quote_info = match attest.attested() {
+        AttestInfo::Quote { info } => info.clone(),
+        other => panic!("Expected a TPMS_QUOTE_INFO attestation, got {other:?}"),
+    };
+
+    let (_update_counter, _selection, pcr_data) = context
+        .execute_with_nullauth_session(|ctx| ctx.pcr_read(pcr_selection_list.clone()))
+        .expect("pcr_read failed");
+
+    let concatenated: Vec<u8> = pcr_data
+        .value()
+        .iter()
+        .flat_map(|d| d.value().to_vec())
+        .collect();
+    let recomputed_digest = hash_with_bank(AK_SCHEME_HASH, &concatenated);
+
+    let pcr_digest_ok = quote_info.pcr_digest().value() == recomputed_digest.as_slice();
+    if pcr_digest_ok {
+        println!("Quote's PCR digest matches a fresh pcr_read - measured boot state confirmed!\n");
+    } else {
+        println!(
+            "Quote's embedded PCR digest does NOT match a fresh pcr_read of the same selection!\n"
+        );
+    }
+
+    context
+        .flush_context(ak_handle.into())
+        .expect("Failed to flush AK handle");
+
+    signature_ok && pcr_digest_ok
+}